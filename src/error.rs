@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Errors returned by the transaction construction and verification helpers.
+#[derive(Debug)]
+pub enum Error {
+    /// A spending transaction's witness did not satisfy the prevout script.
+    #[cfg(feature = "bitcoinconsensus")]
+    ScriptVerification(String),
+    /// The amount requested to spend exceeds the value of the funding output,
+    /// or the selected candidate UTXOs cannot cover the targets plus fee.
+    InsufficientFunds,
+    /// A call to a Bitcoin Core RPC endpoint failed.
+    #[cfg(feature = "rpc")]
+    Rpc(String),
+    /// Building a PSBT from an unsigned transaction failed.
+    Psbt(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "bitcoinconsensus")]
+            Error::ScriptVerification(msg) => write!(f, "script verification failed: {}", msg),
+            Error::InsufficientFunds => write!(f, "amount to spend exceeds the funded value"),
+            #[cfg(feature = "rpc")]
+            Error::Rpc(msg) => write!(f, "bitcoin core rpc call failed: {}", msg),
+            Error::Psbt(msg) => write!(f, "failed to build psbt: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,31 +1,86 @@
 use bitcoin::network::constants::Network;
 use bitcoin::util::address::Address;
-use bitcoin::consensus::encode::{serialize, deserialize};
-use bitcoin::blockdata::script::Builder;
-use bitcoin::blockdata::transaction::{Transaction, TxIn, TxOut};
-use bitcoin::blockdata::opcodes::all::{OP_SHA256, OP_EQUAL};
-use std::str::FromStr;
+use bitcoin::util::bip143::SigHashCache;
+use bitcoin::util::key::PublicKey;
+use bitcoin::consensus::encode::serialize;
+#[cfg(test)]
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::blockdata::transaction::{OutPoint, SigHashType, Transaction, TxIn, TxOut};
+use bitcoin::blockdata::opcodes::all::{
+    OP_CHECKSIG, OP_CLTV, OP_DROP, OP_ELSE, OP_ENDIF, OP_EQUAL, OP_IF, OP_SHA256,
+};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::Amount;
 
-fn generate_redeem_script(preimage: &str, transaction: &Transaction) -> String {
-    let preimage_bytes = hex::decode(preimage).unwrap();
-    let lock_hex = bitcoin::util::bip143::SigHashCache::new(transaction)
-        .output_single(0, &Builder::new().push_slice(&preimage_bytes).into_script())
-        .script_code(&Builder::new().push_opcode(OP_SHA256).push_slice(&preimage_bytes).push_opcode(OP_EQUAL).into_script())
-        .build()
-        .to_hex();
-    format!("OP_SHA256 {} OP_EQUAL", lock_hex)
+mod error;
+pub(crate) mod psbt;
+#[cfg(feature = "rpc")]
+mod rpc;
+mod tx_builder;
+#[cfg(feature = "bitcoinconsensus")]
+mod verify;
+#[cfg(feature = "rpc")]
+mod watcher;
+
+use error::Error;
+use tx_builder::TxBuilder;
+
+/// Which branch of the HTLC redeem script a spending transaction satisfies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SpendPath {
+    /// The hashlock branch: reveal the SHA256 preimage.
+    Preimage,
+    /// The timeout branch: wait for `locktime` and reclaim with the refund key.
+    Timeout,
 }
 
+/// Builds a two-branch HTLC redeem script in the style used by coinswap/teleport:
+///
+/// ```text
+/// OP_IF
+///     OP_SHA256 <hash> OP_EQUAL
+/// OP_ELSE
+///     <locktime> OP_CLTV OP_DROP <refund_pubkey> OP_CHECKSIG
+/// OP_ENDIF
+/// ```
+///
+/// The IF branch lets the redeemer spend with the preimage; the ELSE branch lets
+/// `refund_pubkey` reclaim the funds once `locktime` has passed.
+///
+/// `OP_IF` pops the top witness item as its branch selector, so a witness
+/// spending this script must push one alongside the spending data: a truthy
+/// byte string (e.g. `0x01`) to take the preimage branch, or an empty vector
+/// to fall through to `OP_ELSE` for the timeout branch. See
+/// `construct_spending_transaction` for the witness stacks this contract
+/// requires.
+fn generate_redeem_script(hash: &[u8], refund_pubkey: &PublicKey, locktime: u32) -> Script {
+    Builder::new()
+        .push_opcode(OP_IF)
+        .push_opcode(OP_SHA256)
+        .push_slice(hash)
+        .push_opcode(OP_EQUAL)
+        .push_opcode(OP_ELSE)
+        .push_int(locktime as i64)
+        .push_opcode(OP_CLTV)
+        .push_opcode(OP_DROP)
+        .push_key(refund_pubkey)
+        .push_opcode(OP_CHECKSIG)
+        .push_opcode(OP_ENDIF)
+        .into_script()
+}
 
-fn derive_address(redeem_script: &str) -> Address {
-    let redeem_script_bytes = hex::decode(redeem_script).unwrap();
-    let script = Builder::new().push_slice(&redeem_script_bytes).into_script();
-    Address::from_script(&script, Network::Testnet).unwrap()
+fn derive_address(redeem_script: &Script) -> Address {
+    Address::p2wsh(redeem_script, Network::Testnet)
 }
 
-fn construct_transaction(target_address: &Address, amount: u64) -> Transaction {
+/// Only reachable from `main()` when the `rpc` feature is off, since that
+/// build funds the hashlock address from a real node's wallet instead.
+#[cfg_attr(feature = "rpc", allow(dead_code))]
+fn construct_transaction(target_address: &Address, amount: Amount) -> Transaction {
     let txout = TxOut {
-        value: amount,
+        value: amount.as_sat(),
         script_pubkey: target_address.script_pubkey(),
     };
     Transaction {
@@ -36,32 +91,280 @@ fn construct_transaction(target_address: &Address, amount: u64) -> Transaction {
     }
 }
 
-fn construct_spending_transaction(
+/// Computes the BIP143 sighash for `input_index` of `tx` spending a P2WSH
+/// output locked by `witness_script` worth `amount`, signs it with `key`, and
+/// returns the DER-encoded signature (with the sighash type byte appended).
+fn sign_input(
+    tx: &Transaction,
+    input_index: usize,
+    witness_script: &Script,
+    amount: u64,
+    key: &SecretKey,
+    sighash_type: SigHashType,
+) -> Vec<u8> {
+    let sighash = SigHashCache::new(tx).signature_hash(input_index, witness_script, amount, sighash_type);
+    let secp = Secp256k1::signing_only();
+    let message = Message::from_slice(&sighash[..]).expect("sighash is always 32 bytes");
+    let signature = secp.sign(&message, key);
+    let mut sig_bytes = signature.serialize_der().to_vec();
+    sig_bytes.push(sighash_type.as_u32() as u8);
+    sig_bytes
+}
+
+/// Builds the unsigned shape of a hashlock spend: one input spending
+/// `prev_transaction`'s output at `funding_vout`, an output paying
+/// `amount_to_spend` to `change_address`, a change output locked under
+/// `redeem_script`, and the lock_time/sequence pair required for the chosen
+/// branch. Shared by
+/// [`construct_spending_transaction`] and [`psbt::to_psbt`], which differ
+/// only in how (or whether) the input ends up signed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn build_unsigned_spend(
     prev_transaction: &Transaction,
-    redeem_script: &str,
-    amount_to_spend: u64,
+    funding_vout: u32,
+    redeem_script: &Script,
+    amount_to_spend: Amount,
     change_address: &Address,
-) -> Transaction {
-    let txid = prev_transaction.txid();
+    spend_path: SpendPath,
+    locktime: u32,
+) -> Result<Transaction, Error> {
+    let prev_value = Amount::from_sat(prev_transaction.output[funding_vout as usize].value);
+    let change_value = prev_value
+        .checked_sub(amount_to_spend)
+        .ok_or(Error::InsufficientFunds)?;
+
     let txin = TxIn {
-        previous_output: txid.into(),
-        script_sig: Builder::new().push_slice(&hex::decode(redeem_script).unwrap()).into_script(),
-        sequence: 0xFFFFFFFF,
+        previous_output: OutPoint::new(prev_transaction.txid(), funding_vout),
+        script_sig: Script::new(),
+        // CLTV only constrains a transaction whose input is not using the final
+        // sequence number, so the timeout branch must use a non-final sequence.
+        sequence: match spend_path {
+            SpendPath::Preimage => 0xFFFFFFFF,
+            SpendPath::Timeout => 0xFFFFFFFE,
+        },
         witness: Vec::new(),
     };
     let txout1 = TxOut {
-        value: amount_to_spend,
+        value: amount_to_spend.as_sat(),
         script_pubkey: change_address.script_pubkey(),
     };
     let txout2 = TxOut {
-        value: prev_transaction.output[0].value - amount_to_spend,
-        script_pubkey: prev_transaction.output[0].script_pubkey.clone(),
+        value: change_value.as_sat(),
+        // Leftover value stays locked under the same HTLC covenant rather
+        // than being copied verbatim from the prevout, so it's derived from
+        // `redeem_script` instead of `prev_transaction`.
+        script_pubkey: derive_address(redeem_script).script_pubkey(),
     };
-    Transaction {
+    Ok(Transaction {
         version: 1,
-        lock_time: 0,
+        // CLTV is only enforced when the transaction's lock_time is set to at
+        // least the value being checked, so the timeout branch needs it here too.
+        lock_time: match spend_path {
+            SpendPath::Preimage => 0,
+            SpendPath::Timeout => locktime,
+        },
         input: vec![txin],
         output: vec![txout1, txout2],
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn construct_spending_transaction(
+    prev_transaction: &Transaction,
+    funding_vout: u32,
+    redeem_script: &Script,
+    amount_to_spend: Amount,
+    change_address: &Address,
+    spend_path: SpendPath,
+    locktime: u32,
+    preimage: &[u8],
+    refund_key: &SecretKey,
+) -> Result<Transaction, Error> {
+    let prev_value = Amount::from_sat(prev_transaction.output[funding_vout as usize].value);
+    let mut spending_transaction = build_unsigned_spend(
+        prev_transaction,
+        funding_vout,
+        redeem_script,
+        amount_to_spend,
+        change_address,
+        spend_path,
+        locktime,
+    )?;
+
+    // OP_IF pops the top witness item as its branch selector before the
+    // hashlock/timeout logic runs, so a selector must be pushed alongside
+    // the spending data: truthy (`0x01`) to take the preimage branch, empty
+    // (falsy) to fall through to OP_ELSE for the timeout branch.
+    let witness = match spend_path {
+        SpendPath::Preimage => vec![
+            preimage.to_vec(),
+            vec![0x01],
+            redeem_script.as_bytes().to_vec(),
+        ],
+        SpendPath::Timeout => {
+            let signature = sign_input(
+                &spending_transaction,
+                0,
+                redeem_script,
+                prev_value.as_sat(),
+                refund_key,
+                SigHashType::All,
+            );
+            vec![signature, Vec::new(), redeem_script.as_bytes().to_vec()]
+        }
+    };
+    spending_transaction.input[0].witness = witness;
+    Ok(spending_transaction)
+}
+
+fn main() {
+    let preimage = "427472757374204275696c64657273";
+    let preimage_bytes = hex::decode(preimage).unwrap();
+    let hash = bitcoin::hashes::sha256::Hash::hash(&preimage_bytes);
+    let secp = Secp256k1::new();
+    let refund_key = SecretKey::from_slice(&[0x11u8; 32]).unwrap();
+    let refund_pubkey = PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &refund_key));
+    let locktime = 500_000u32;
+    let redeem_script = generate_redeem_script(&hash[..], &refund_pubkey, locktime);
+    let target_address = derive_address(&redeem_script);
+
+    println!("Redeem Script: {}", redeem_script.asm());
+    println!("Derived Address: {}", target_address);
+
+    let amount = Amount::from_sat(50000);
+
+    // Demonstrate coin selection against a wallet's UTXO set. The resulting
+    // transaction is only printed: the spends below are built on the funding
+    // transaction from the node's wallet (`rpc` feature) or from
+    // `construct_transaction`.
+    let wallet_key = SecretKey::from_slice(&[0x22u8; 32]).unwrap();
+    let wallet_pubkey = PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &wallet_key));
+    let wallet_script = Address::p2wpkh(&wallet_pubkey, Network::Testnet).unwrap().script_pubkey();
+    let wallet_utxo = Transaction {
+        version: 1,
+        lock_time: 0,
+        input: Vec::new(),
+        output: vec![TxOut { value: 1_000_000, script_pubkey: wallet_script.clone() }],
+    };
+    let candidates = vec![(OutPoint::new(wallet_utxo.txid(), 0), wallet_utxo.output[0].clone())];
+    let builder = TxBuilder::new(candidates, 1);
+    let targets = vec![TxOut { value: amount.as_sat(), script_pubkey: target_address.script_pubkey() }];
+    let (funding_transaction, prevouts) = builder
+        .build(targets, wallet_script)
+        .expect("wallet UTXO set should cover the funding amount plus fee");
+    println!(
+        "Coin-selected Funding Transaction ({} prevout(s)):\n{}",
+        prevouts.len(),
+        hex::encode(serialize(&funding_transaction))
+    );
+
+    #[cfg(feature = "rpc")]
+    let (transaction, funding_vout) = {
+        // End-to-end: fund the derived address on a running node instead of
+        // only constructing a standalone transaction in memory.
+        let client = bitcoincore_rpc::Client::new(
+            "http://127.0.0.1:18332".to_string(),
+            bitcoincore_rpc::Auth::CookieFile("/root/.bitcoin/testnet3/.cookie".into()),
+        )
+        .expect("failed to connect to bitcoind");
+        let (transaction, funding_vout) =
+            rpc::fund_address(&client, &target_address, amount).expect("failed to fund hashlock address");
+
+        // Confirm the payment is visible the same way a caller without the
+        // funding transaction in hand would learn the address was paid.
+        let watched_script = target_address.script_pubkey();
+        let mut watcher = watcher::Watcher::new(client, vec![watched_script.clone()], 0);
+        watcher.scan_mempool().expect("failed to scan mempool for the funding payment");
+        watcher.scan_new_blocks().expect("failed to scan blocks for the funding payment");
+        for payment in watcher.payments_to(&watched_script) {
+            println!(
+                "Watcher observed funding payment {}:{} ({} sats, {} confirmations)",
+                payment.txid, payment.vout, payment.value, payment.confirmations
+            );
+        }
+
+        (transaction, funding_vout)
+    };
+    #[cfg(not(feature = "rpc"))]
+    let (transaction, funding_vout) = (construct_transaction(&target_address, amount), 0u32);
+
+    println!("Constructed Transaction:\n{}", hex::encode(serialize(&transaction)));
+
+    let change_address = derive_address(&redeem_script);
+    let spending_transaction = construct_spending_transaction(
+        &transaction,
+        funding_vout,
+        &redeem_script,
+        Amount::from_sat(10000),
+        &change_address,
+        SpendPath::Preimage,
+        locktime,
+        &preimage_bytes,
+        &refund_key,
+    )
+    .expect("amount to spend does not exceed the funded value");
+
+    println!("Spending Transaction:\n{}", hex::encode(serialize(&spending_transaction)));
+
+    // Also demonstrate the timeout branch, which refunds the refund key once
+    // the locktime has passed instead of requiring the preimage.
+    let timeout_spending_transaction = construct_spending_transaction(
+        &transaction,
+        funding_vout,
+        &redeem_script,
+        Amount::from_sat(10000),
+        &change_address,
+        SpendPath::Timeout,
+        locktime,
+        &preimage_bytes,
+        &refund_key,
+    )
+    .expect("amount to spend does not exceed the funded value");
+    println!(
+        "Timeout-path Spending Transaction:\n{}",
+        hex::encode(serialize(&timeout_spending_transaction))
+    );
+
+    // Export the same spend as an unsigned PSBT so it can be signed by an
+    // offline signer or hardware wallet instead of the in-process secret key.
+    let psbt_params = psbt::SpendParams {
+        funding_vout,
+        redeem_script: redeem_script.clone(),
+        amount_to_spend: Amount::from_sat(10000),
+        change_address: change_address.clone(),
+        spend_path: SpendPath::Preimage,
+        locktime,
+    };
+    let unsigned_psbt = psbt::to_psbt(&transaction, &psbt_params).expect("failed to build PSBT for the hashlock spend");
+    println!("Unsigned PSBT:\n{}", hex::encode(serialize(&unsigned_psbt)));
+
+    // Finalize the PSBT with the preimage witness, mirroring what an offline
+    // signer would hand back, and recover the spending transaction from it.
+    let mut signed_psbt = unsigned_psbt;
+    signed_psbt.inputs[0].final_script_witness = Some(vec![
+        preimage_bytes.clone(),
+        vec![0x01],
+        redeem_script.as_bytes().to_vec(),
+    ]);
+    let finalized_transaction = psbt::finalize_psbt(signed_psbt);
+    println!("Finalized PSBT Transaction:\n{}", hex::encode(serialize(&finalized_transaction)));
+
+    #[cfg(feature = "bitcoinconsensus")]
+    match verify::verify_spend(&transaction, &spending_transaction) {
+        Ok(()) => println!("Spend verified against the Bitcoin Core script engine"),
+        Err(e) => println!("Spend verification failed: {}", e),
+    }
+
+    #[cfg(feature = "rpc")]
+    {
+        let client = bitcoincore_rpc::Client::new(
+            "http://127.0.0.1:18332".to_string(),
+            bitcoincore_rpc::Auth::CookieFile("/root/.bitcoin/testnet3/.cookie".into()),
+        )
+        .expect("failed to connect to bitcoind");
+        let txid = rpc::broadcast(&client, &spending_transaction).expect("failed to broadcast spend");
+        rpc::wait_for_confirmations(&client, &txid, 1).expect("failed waiting for confirmations");
+        println!("Spend confirmed: {}", txid);
     }
 }
 
@@ -69,71 +372,308 @@ fn construct_spending_transaction(
 mod tests {
     use super::*;
 
+    fn refund_key() -> SecretKey {
+        SecretKey::from_slice(&[0x11u8; 32]).unwrap()
+    }
+
+    fn refund_pubkey() -> PublicKey {
+        let secp = Secp256k1::new();
+        PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &refund_key()))
+    }
+
+    #[test]
+    fn test_generate_redeem_script_contains_both_branches() {
+        let hash = [0x11u8; 32];
+        let redeem_script = generate_redeem_script(&hash, &refund_pubkey(), 500_000);
+        let asm = redeem_script.asm();
+        assert!(asm.contains("OP_IF"));
+        assert!(asm.contains("OP_SHA256"));
+        assert!(asm.contains("OP_ELSE"));
+        assert!(asm.contains("OP_CLTV"));
+        assert!(asm.contains("OP_DROP"));
+        assert!(asm.contains("OP_CHECKSIG"));
+        assert!(asm.contains("OP_ENDIF"));
+    }
+
+    /// Pins the opcode order of the two branches, not just their presence, so
+    /// the IF-branch-first layout this crate's witness assembly depends on
+    /// (see `generate_redeem_script`'s doc comment and
+    /// `construct_spending_transaction`) can't silently drift.
     #[test]
-    fn test_generate_redeem_script() {
-        let preimage = "427472757374204275696c64657273";
-        let redeem_script = generate_redeem_script(preimage);
-        assert_eq!(redeem_script, "OP_SHA256 0100000000000000000000000000000000000000000000000000000000000000 OP_EQUAL");
+    fn test_generate_redeem_script_branch_order_matches_witness_contract() {
+        let hash = [0x11u8; 32];
+        let redeem_script = generate_redeem_script(&hash, &refund_pubkey(), 500_000);
+        let asm = redeem_script.asm();
+        let opcodes: Vec<&str> = asm.split_whitespace().collect();
+
+        let if_pos = opcodes.iter().position(|op| *op == "OP_IF").unwrap();
+        let sha256_pos = opcodes.iter().position(|op| *op == "OP_SHA256").unwrap();
+        let else_pos = opcodes.iter().position(|op| *op == "OP_ELSE").unwrap();
+        let cltv_pos = opcodes.iter().position(|op| *op == "OP_CLTV").unwrap();
+        let checksig_pos = opcodes.iter().position(|op| *op == "OP_CHECKSIG").unwrap();
+        let endif_pos = opcodes.iter().position(|op| *op == "OP_ENDIF").unwrap();
+
+        assert!(if_pos < sha256_pos, "preimage branch must follow OP_IF");
+        assert!(sha256_pos < else_pos, "hashlock check must precede OP_ELSE");
+        assert!(else_pos < cltv_pos, "timeout branch must follow OP_ELSE");
+        assert!(cltv_pos < checksig_pos);
+        assert!(checksig_pos < endif_pos, "OP_ENDIF must close both branches");
     }
 
     #[test]
-    fn test_derive_address() {
-        let redeem_script = "OP_SHA256 0100000000000000000000000000000000000000000000000000000000000000 OP_EQUAL";
-        let address = derive_address(redeem_script);
-        assert_eq!(address.to_string(), "tb1qzxzjgakmhrqhq0s37lkxrn6j74vqvp3v7r6x2k");
+    fn test_derive_address_is_p2wsh() {
+        let hash = [0x22u8; 32];
+        let redeem_script = generate_redeem_script(&hash, &refund_pubkey(), 500_000);
+        let address = derive_address(&redeem_script);
+        assert!(address.to_string().starts_with("tb1q"));
+        assert_eq!(address.script_pubkey(), Address::p2wsh(&redeem_script, Network::Testnet).script_pubkey());
     }
 
     #[test]
     fn test_construct_transaction() {
-        let target_address = Address::from_str("tb1qzxzjgakmhrqhq0s37lkxrn6j74vqvp3v7r6x2k").unwrap();
-        let amount = 50000;
+        let hash = [0x33u8; 32];
+        let redeem_script = generate_redeem_script(&hash, &refund_pubkey(), 500_000);
+        let target_address = derive_address(&redeem_script);
+        let amount = Amount::from_sat(50000);
         let transaction = construct_transaction(&target_address, amount);
         assert_eq!(transaction.output.len(), 1);
-        assert_eq!(transaction.output[0].value, amount);
+        assert_eq!(transaction.output[0].value, amount.as_sat());
         assert_eq!(transaction.output[0].script_pubkey, target_address.script_pubkey());
     }
 
     #[test]
-    fn test_construct_spending_transaction() {
-        let redeem_script = "OP_SHA256 0100000000000000000000000000000000000000000000000000000000000000 OP_EQUAL";
-        let prev_transaction_hex = "010000000001010000000000000000000000000000000000000000000000000000000000000000ffffffff04011b0b64197676a9143a609ee60f8bb8be750af949137eaa3aeebd2ec88ac0000000000000000143079a50698a02f2c61a1ed5a58b8a5d2b642ae173f00000000";
+    fn test_construct_spending_transaction_preimage_path() {
+        let preimage = [0x44u8; 32];
+        let hash = bitcoin::hashes::sha256::Hash::hash(&preimage);
+        let redeem_script = generate_redeem_script(&hash[..], &refund_pubkey(), 500_000);
+        let prev_transaction_hex = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff04011b0b64ffffffff0150c3000000000000160014000000000000000000000000000000000000000000000000";
         let prev_transaction_bytes = hex::decode(prev_transaction_hex).unwrap();
         let prev_transaction: Transaction = deserialize(&prev_transaction_bytes).unwrap();
-        let amount_to_spend = 5000;
-        let change_address = derive_address(redeem_script);
-        let spending_transaction = construct_spending_transaction(&prev_transaction, redeem_script, amount_to_spend, &change_address);
-        assert_eq!(spending_transaction.input.len(), 1);
-        assert_eq!(spending_transaction.output.len(), 2);
-        assert_eq!(spending_transaction.output[0].value, amount_to_spend);
-        assert_eq!(spending_transaction.output[1].value, prev_transaction.output[0].value - amount_to_spend);
-        assert_eq!(spending_transaction.output[0].script_pubkey, change_address.script_pubkey());
-        assert_eq!(spending_transaction.output[1].script_pubkey, prev_transaction.output[0].script_pubkey);
+        let amount_to_spend = Amount::from_sat(5000);
+        let change_address = derive_address(&redeem_script);
+        let spending_transaction = construct_spending_transaction(
+            &prev_transaction,
+            0,
+            &redeem_script,
+            amount_to_spend,
+            &change_address,
+            SpendPath::Preimage,
+            500_000,
+            &preimage,
+            &refund_key(),
+        )
+        .unwrap();
+        assert_eq!(spending_transaction.lock_time, 0);
+        assert_eq!(spending_transaction.input[0].sequence, 0xFFFFFFFF);
+        assert_eq!(spending_transaction.input[0].script_sig, Script::new());
+        assert_eq!(
+            spending_transaction.input[0].witness,
+            vec![preimage.to_vec(), vec![0x01], redeem_script.as_bytes().to_vec()]
+        );
+        assert_eq!(spending_transaction.output[0].value, amount_to_spend.as_sat());
+        assert_eq!(spending_transaction.output[1].value, prev_transaction.output[0].value - amount_to_spend.as_sat());
     }
-}
 
-fn main() {
-    let preimage = "427472757374204275696c64657273";
-    let transaction = Transaction::default(); // Create a default transaction
-    let redeem_script = generate_redeem_script(preimage, &transaction);
-    let target_address = derive_address(&redeem_script);
+    #[test]
+    fn test_construct_spending_transaction_rejects_amount_over_prevout_value() {
+        let preimage = [0x77u8; 32];
+        let hash = bitcoin::hashes::sha256::Hash::hash(&preimage);
+        let redeem_script = generate_redeem_script(&hash[..], &refund_pubkey(), 500_000);
+        let prev_transaction_hex = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff04011b0b64ffffffff0150c3000000000000160014000000000000000000000000000000000000000000000000";
+        let prev_transaction_bytes = hex::decode(prev_transaction_hex).unwrap();
+        let prev_transaction: Transaction = deserialize(&prev_transaction_bytes).unwrap();
+        let change_address = derive_address(&redeem_script);
+        let amount_to_spend = Amount::from_sat(prev_transaction.output[0].value + 1);
+        let result = construct_spending_transaction(
+            &prev_transaction,
+            0,
+            &redeem_script,
+            amount_to_spend,
+            &change_address,
+            SpendPath::Preimage,
+            500_000,
+            &preimage,
+            &refund_key(),
+        );
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+    }
 
-    println!("Redeem Script: {}", redeem_script);
-    println!("Derived Address: {}", target_address);
+    /// `Transaction` has no `Default` impl, so coin-selection tests that only
+    /// need a distinct, deterministic outpoint build one from an empty
+    /// transaction instead.
+    fn dummy_outpoint(vout: u32) -> OutPoint {
+        let empty_tx = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        OutPoint::new(empty_tx.txid(), vout)
+    }
+
+    #[test]
+    fn test_tx_builder_selects_largest_utxos_first_and_subtracts_fee() {
+        let change_script = derive_address(&generate_redeem_script(&[0x88u8; 32], &refund_pubkey(), 500_000))
+            .script_pubkey();
+        let target_script = change_script.clone();
+        let candidates = vec![
+            (
+                dummy_outpoint(0),
+                TxOut { value: 1_000, script_pubkey: change_script.clone() },
+            ),
+            (
+                dummy_outpoint(1),
+                TxOut { value: 100_000, script_pubkey: change_script.clone() },
+            ),
+        ];
+        let builder = TxBuilder::new(candidates, 1);
+        let targets = vec![TxOut { value: 50_000, script_pubkey: target_script }];
+        let (tx, prevouts) = builder.build(targets, change_script).unwrap();
 
-    let amount = 50000;
-    let transaction = construct_transaction(&target_address, amount);
+        assert_eq!(prevouts.len(), 1);
+        assert_eq!(prevouts[0].value, 100_000);
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].value, 50_000);
+        assert!(tx.output[1].value < 100_000 - 50_000);
+    }
 
-    println!("Constructed Transaction:\n{}", serialize_hex(&transaction));
+    #[test]
+    fn test_tx_builder_fails_when_candidates_cannot_cover_target_and_fee() {
+        let change_script = derive_address(&generate_redeem_script(&[0x99u8; 32], &refund_pubkey(), 500_000))
+            .script_pubkey();
+        let candidates = vec![(
+            dummy_outpoint(0),
+            TxOut { value: 1_000, script_pubkey: change_script.clone() },
+        )];
+        let builder = TxBuilder::new(candidates, 1);
+        let targets = vec![TxOut { value: 50_000, script_pubkey: change_script.clone() }];
+        let result = builder.build(targets, change_script);
+        assert!(matches!(result, Err(Error::InsufficientFunds)));
+    }
 
-    let redeem_script_hex = "OP_SHA256 010000000000000000000000000000000000000000000000000000000000000000 OP_EQUAL";
-    let change_address = derive_address(&redeem_script_hex);
-    let spending_transaction = construct_spending_transaction(
-        &transaction,
-        &redeem_script_hex,
-        10000,
-        &change_address,
-    );
+    #[test]
+    fn test_construct_spending_transaction_timeout_path_sets_locktime_and_signs() {
+        let preimage = [0x55u8; 32];
+        let hash = bitcoin::hashes::sha256::Hash::hash(&preimage);
+        let redeem_script = generate_redeem_script(&hash[..], &refund_pubkey(), 500_000);
+        let prev_transaction_hex = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff04011b0b64ffffffff0150c3000000000000160014000000000000000000000000000000000000000000000000";
+        let prev_transaction_bytes = hex::decode(prev_transaction_hex).unwrap();
+        let prev_transaction: Transaction = deserialize(&prev_transaction_bytes).unwrap();
+        let amount_to_spend = Amount::from_sat(5000);
+        let change_address = derive_address(&redeem_script);
+        let spending_transaction = construct_spending_transaction(
+            &prev_transaction,
+            0,
+            &redeem_script,
+            amount_to_spend,
+            &change_address,
+            SpendPath::Timeout,
+            500_000,
+            &preimage,
+            &refund_key(),
+        )
+        .unwrap();
+        assert_eq!(spending_transaction.lock_time, 500_000);
+        assert_eq!(spending_transaction.input[0].sequence, 0xFFFFFFFE);
+        assert_eq!(spending_transaction.input[0].witness.len(), 3);
+        assert_eq!(spending_transaction.input[0].witness[1], Vec::<u8>::new());
+        assert_eq!(spending_transaction.input[0].witness[2], redeem_script.as_bytes().to_vec());
+    }
 
-    println!("Spending Transaction:\n{}", serialize_hex(&spending_transaction));
+    #[cfg(feature = "bitcoinconsensus")]
+    #[test]
+    fn test_verify_spend_accepts_timeout_path_witness() {
+        let preimage = [0x66u8; 32];
+        let hash = bitcoin::hashes::sha256::Hash::hash(&preimage);
+        let redeem_script = generate_redeem_script(&hash[..], &refund_pubkey(), 500_000);
+        let hashlock_address = derive_address(&redeem_script);
+        // The prevout must actually pay the HTLC address for the consensus
+        // engine to accept the witness.
+        let prev_transaction = construct_transaction(&hashlock_address, Amount::from_sat(50_000));
+        let change_address = derive_address(&redeem_script);
+        let spending_transaction = construct_spending_transaction(
+            &prev_transaction,
+            0,
+            &redeem_script,
+            Amount::from_sat(5000),
+            &change_address,
+            SpendPath::Timeout,
+            500_000,
+            &preimage,
+            &refund_key(),
+        )
+        .unwrap();
+        assert!(crate::verify::verify_spend(&prev_transaction, &spending_transaction).is_ok());
+    }
+
+    // Requires a running testnet bitcoind reachable with the cookie file
+    // below; run explicitly with `cargo test --features rpc -- --ignored`.
+    #[cfg(feature = "rpc")]
+    #[test]
+    #[ignore]
+    fn test_fund_and_broadcast_htlc_spend_over_rpc() {
+        let redeem_script = generate_redeem_script(&[0xaau8; 32], &refund_pubkey(), 500_000);
+        let hashlock_address = derive_address(&redeem_script);
+        let client = bitcoincore_rpc::Client::new(
+            "http://127.0.0.1:18332".to_string(),
+            bitcoincore_rpc::Auth::CookieFile("/root/.bitcoin/testnet3/.cookie".into()),
+        )
+        .unwrap();
+
+        let (prev_transaction, funding_vout) =
+            crate::rpc::fund_address(&client, &hashlock_address, Amount::from_sat(50_000)).unwrap();
+        let change_address = derive_address(&redeem_script);
+        let spending_transaction = construct_spending_transaction(
+            &prev_transaction,
+            funding_vout,
+            &redeem_script,
+            Amount::from_sat(10_000),
+            &change_address,
+            SpendPath::Timeout,
+            500_000,
+            &[0xaau8; 32],
+            &refund_key(),
+        )
+        .unwrap();
+
+        let txid = crate::rpc::broadcast(&client, &spending_transaction).unwrap();
+        crate::rpc::wait_for_confirmations(&client, &txid, 1).unwrap();
+    }
+
+    #[test]
+    fn test_psbt_round_trip_carries_witness_utxo_and_script() {
+        let preimage = [0xbbu8; 32];
+        let hash = bitcoin::hashes::sha256::Hash::hash(&preimage);
+        let redeem_script = generate_redeem_script(&hash[..], &refund_pubkey(), 500_000);
+        let hashlock_address = derive_address(&redeem_script);
+        let prev_transaction = construct_transaction(&hashlock_address, Amount::from_sat(50_000));
+
+        let params = psbt::SpendParams {
+            funding_vout: 0,
+            redeem_script: redeem_script.clone(),
+            amount_to_spend: Amount::from_sat(10_000),
+            change_address: derive_address(&redeem_script),
+            spend_path: SpendPath::Preimage,
+            locktime: 500_000,
+        };
+        let unsigned_psbt = psbt::to_psbt(&prev_transaction, &params).unwrap();
+        assert_eq!(unsigned_psbt.inputs.len(), 1);
+        assert_eq!(unsigned_psbt.inputs[0].witness_utxo, Some(prev_transaction.output[0].clone()));
+        assert_eq!(unsigned_psbt.inputs[0].witness_script, Some(redeem_script.clone()));
+        assert!(unsigned_psbt.global.unsigned_tx.input[0].witness.is_empty());
+
+        let mut signed_psbt = unsigned_psbt;
+        signed_psbt.inputs[0].final_script_witness = Some(vec![
+            preimage.to_vec(),
+            vec![0x01],
+            redeem_script.as_bytes().to_vec(),
+        ]);
+        let finalized = psbt::finalize_psbt(signed_psbt);
+        assert_eq!(
+            finalized.input[0].witness,
+            vec![preimage.to_vec(), vec![0x01], redeem_script.as_bytes().to_vec()]
+        );
+    }
 }
 
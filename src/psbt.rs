@@ -0,0 +1,64 @@
+//! Produces and finalizes a PSBT for the hashlock spend, so the witness can
+//! be supplied by an offline signer or hardware wallet instead of requiring
+//! the secret key in-process.
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::util::address::Address;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::Amount;
+
+use crate::build_unsigned_spend;
+use crate::error::Error;
+use crate::SpendPath;
+
+/// `bitcoin::util::psbt::PartiallySignedTransaction` under the name this
+/// crate's external-signer APIs are written against.
+pub type Psbt = PartiallySignedTransaction;
+
+/// The parameters needed to describe a hashlock spend without the secret
+/// material required to sign it.
+pub struct SpendParams {
+    pub funding_vout: u32,
+    pub redeem_script: Script,
+    pub amount_to_spend: Amount,
+    pub change_address: Address,
+    pub spend_path: SpendPath,
+    pub locktime: u32,
+}
+
+/// Builds the unsigned spend and wraps it in a PSBT with `witness_utxo` and
+/// `witness_script` populated for its single input, ready to be serialized
+/// to base64 and handed to an offline signer.
+pub fn to_psbt(prev_transaction: &Transaction, params: &SpendParams) -> Result<Psbt, Error> {
+    let unsigned_tx = build_unsigned_spend(
+        prev_transaction,
+        params.funding_vout,
+        &params.redeem_script,
+        params.amount_to_spend,
+        &params.change_address,
+        params.spend_path,
+        params.locktime,
+    )?;
+
+    let mut psbt = Psbt::from_unsigned_tx(unsigned_tx).map_err(|e| Error::Psbt(e.to_string()))?;
+    psbt.inputs[0].witness_utxo = Some(prev_transaction.output[params.funding_vout as usize].clone());
+    psbt.inputs[0].witness_script = Some(params.redeem_script.clone());
+    Ok(psbt)
+}
+
+/// Lifts a PSBT's finalized `final_script_sig`/`final_script_witness` fields
+/// from each input back onto the underlying transaction. Assumes every
+/// input has already been finalized by a signer.
+pub fn finalize_psbt(psbt: Psbt) -> Transaction {
+    let mut tx = psbt.global.unsigned_tx;
+    for (index, input) in psbt.inputs.into_iter().enumerate() {
+        if let Some(script_sig) = input.final_script_sig {
+            tx.input[index].script_sig = script_sig;
+        }
+        if let Some(witness) = input.final_script_witness {
+            tx.input[index].witness = witness;
+        }
+    }
+    tx
+}
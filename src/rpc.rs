@@ -0,0 +1,53 @@
+//! Wires the transaction builder to a running Bitcoin Core node so the
+//! hashlock flow can run end-to-end against testnet: fund the derived
+//! address, broadcast the signed spend, and track its confirmations.
+//! Gated behind the `rpc` feature so core builds don't pull in the node
+//! client.
+
+use bitcoin::{Address, Amount, Transaction, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+
+use crate::error::Error;
+
+/// Sends `amount` to `address` via the node's wallet and returns the raw
+/// funding transaction along with the index of the output paying `address`,
+/// so that output can be used as a prevout for a spending transaction.
+/// `send_to_address` gives no guarantee the payment lands at `output[0]`
+/// (the wallet may add a change output ahead of it), so the matching
+/// scriptPubkey is located rather than assumed.
+pub fn fund_address(client: &Client, address: &Address, amount: Amount) -> Result<(Transaction, u32), Error> {
+    let txid = client
+        .send_to_address(address, amount, None, None, None, None, None, None)
+        .map_err(|e| Error::Rpc(e.to_string()))?;
+    let tx = client
+        .get_raw_transaction(&txid, None)
+        .map_err(|e| Error::Rpc(e.to_string()))?;
+    let vout = tx
+        .output
+        .iter()
+        .position(|txout| txout.script_pubkey == address.script_pubkey())
+        .ok_or_else(|| Error::Rpc("funding transaction has no output paying the requested address".to_string()))?
+        as u32;
+    Ok((tx, vout))
+}
+
+/// Submits `tx` to the network via `sendrawtransaction`.
+pub fn broadcast(client: &Client, tx: &Transaction) -> Result<Txid, Error> {
+    client
+        .send_raw_transaction(tx)
+        .map_err(|e| Error::Rpc(e.to_string()))
+}
+
+/// Polls `getrawtransaction` (verbose) once a second until `txid` has at
+/// least `confirmations` confirmations.
+pub fn wait_for_confirmations(client: &Client, txid: &Txid, confirmations: u32) -> Result<(), Error> {
+    loop {
+        let info = client
+            .get_raw_transaction_info(txid, None)
+            .map_err(|e| Error::Rpc(e.to_string()))?;
+        if info.confirmations.unwrap_or(0) >= confirmations {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
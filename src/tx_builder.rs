@@ -0,0 +1,106 @@
+//! Coin selection and fee estimation for funding a spending transaction from
+//! a set of candidate UTXOs.
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+
+use crate::error::Error;
+
+/// Bitcoin Core's default dust relay threshold for a P2WSH output, in sats.
+const DUST_THRESHOLD_SAT: u64 = 330;
+
+/// Estimated signed vsize, in vbytes, of spending a single P2WSH HTLC input
+/// (outpoint + empty script_sig + a `[signature, branch selector,
+/// witness_script]` witness).
+const HTLC_INPUT_VSIZE: u64 = 112;
+/// Estimated vsize of the version/locktime/in-count/out-count overhead.
+const BASE_TX_VSIZE: u64 = 11;
+/// Estimated vsize of a single P2WSH-sized output.
+const OUTPUT_VSIZE: u64 = 43;
+
+/// Selects UTXOs and assembles a ready-to-sign transaction that funds
+/// `targets` at a given fee rate.
+pub struct TxBuilder {
+    candidates: Vec<(OutPoint, TxOut)>,
+    fee_rate_sat_per_vb: u64,
+}
+
+impl TxBuilder {
+    pub fn new(candidates: Vec<(OutPoint, TxOut)>, fee_rate_sat_per_vb: u64) -> Self {
+        TxBuilder {
+            candidates,
+            fee_rate_sat_per_vb,
+        }
+    }
+
+    /// Performs largest-first coin selection against `targets`, subtracts the
+    /// estimated fee from a trailing change output paid to `change_script`
+    /// (dropping it if it would be dust), and returns the unsigned
+    /// transaction along with the prevouts selected for it, in input order,
+    /// for later BIP143 sighash computation.
+    pub fn build(
+        &self,
+        targets: Vec<TxOut>,
+        change_script: Script,
+    ) -> Result<(Transaction, Vec<TxOut>), Error> {
+        let target_value: u64 = targets.iter().map(|txout| txout.value).sum();
+
+        let mut candidates = self.candidates.clone();
+        candidates.sort_by_key(|(_, txout)| std::cmp::Reverse(txout.value));
+
+        let mut selected: Vec<(OutPoint, TxOut)> = Vec::new();
+        let mut selected_value = 0u64;
+        for (outpoint, txout) in candidates {
+            selected_value += txout.value;
+            selected.push((outpoint, txout));
+            if selected_value >= target_value + self.estimate_fee(selected.len(), targets.len() + 1) {
+                break;
+            }
+        }
+
+        let fee = self.estimate_fee(selected.len(), targets.len() + 1);
+        let required = target_value
+            .checked_add(fee)
+            .ok_or(Error::InsufficientFunds)?;
+        if selected_value < required {
+            return Err(Error::InsufficientFunds);
+        }
+
+        let change_value = selected_value - required;
+
+        let input = selected
+            .iter()
+            .map(|(outpoint, _)| TxIn {
+                previous_output: *outpoint,
+                script_sig: Script::new(),
+                sequence: 0xFFFFFFFF,
+                witness: Vec::new(),
+            })
+            .collect();
+
+        let mut output = targets;
+        if change_value >= DUST_THRESHOLD_SAT {
+            output.push(TxOut {
+                value: change_value,
+                script_pubkey: change_script,
+            });
+        }
+
+        let prevouts = selected.into_iter().map(|(_, txout)| txout).collect();
+        let transaction = Transaction {
+            version: 1,
+            lock_time: 0,
+            input,
+            output,
+        };
+
+        Ok((transaction, prevouts))
+    }
+
+    fn estimate_fee(&self, input_count: usize, output_count: usize) -> u64 {
+        let vsize = BASE_TX_VSIZE
+            + input_count as u64 * HTLC_INPUT_VSIZE
+            + output_count as u64 * OUTPUT_VSIZE;
+        vsize * self.fee_rate_sat_per_vb
+    }
+}
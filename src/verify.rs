@@ -0,0 +1,30 @@
+//! Verifies constructed spending transactions against the real Bitcoin Core
+//! script engine. Gated behind the `bitcoinconsensus` feature so that core
+//! builds of this crate don't pull in the libconsensus C dependency.
+
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode::serialize;
+
+use crate::error::Error;
+
+/// Runs `spending_tx`'s inputs through `bitcoinconsensus::verify_with_flags`
+/// against the outputs they spend from `prev_tx`, confirming the witness
+/// actually satisfies the prevout script before the transaction is broadcast.
+pub fn verify_spend(prev_tx: &Transaction, spending_tx: &Transaction) -> Result<(), Error> {
+    let spending_tx_bytes = serialize(spending_tx);
+    let flags = bitcoinconsensus::VERIFY_P2SH | bitcoinconsensus::VERIFY_WITNESS;
+
+    for (index, txin) in spending_tx.input.iter().enumerate() {
+        let prevout = &prev_tx.output[txin.previous_output.vout as usize];
+        bitcoinconsensus::verify_with_flags(
+            prevout.script_pubkey.as_bytes(),
+            prevout.value,
+            &spending_tx_bytes,
+            index,
+            flags,
+        )
+        .map_err(|e| Error::ScriptVerification(format!("{:?}", e)))?;
+    }
+
+    Ok(())
+}